@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
-use axum::{Json, extract::{State, Path}, http::StatusCode};
+use axum::{Json, extract::{State, Path}, http::StatusCode, Extension};
+use crate::auth::{generate_api_credentials, AuthenticatedAddress};
 use crate::handlers::trading::TradingState;
+use crate::storage::{PersistenceBackend, Storage, StorageError};
 use crate::AppState;
 use crate::handlers::trading::Investor;
-use tfhe::{FheUint8, ServerKey, set_server_key, ClientKey};
+use tfhe::{set_server_key, CompressedCiphertextList, CompressedCiphertextListBuilder, FheBool, FheUint8};
 use tfhe::prelude::*;
 
 
@@ -15,6 +17,16 @@ pub struct Account {
     strategy_ids: Vec<u128>,
     limits_orders_long: HashMap<u128, LimitsOrderLong>,
     limits_orders_short: HashMap<u128, LimitsOrderShort>,
+    #[serde(skip)]
+    api_key: String,
+    #[serde(skip)]
+    api_secret: String,
+}
+
+impl Account {
+    pub fn balance(&self) -> u128 {
+        self.balance
+    }
 }
 
 #[derive(Clone, Serialize)]
@@ -26,6 +38,12 @@ pub struct LimitsOrderLong {
     profit: FheUint8,
 }
 
+impl LimitsOrderLong {
+    pub fn new(owner: String, token: String, asset: String, stop: FheUint8, profit: FheUint8) -> Self {
+        Self { owner, token, asset, stop, profit }
+    }
+}
+
 #[derive(Clone, Serialize)]
 pub struct LimitsOrderShort {
     owner: String,
@@ -41,6 +59,12 @@ pub struct AccountState {
     accounts: HashMap<String, Account>,
 }
 
+impl Default for AccountState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AccountState {
     pub fn new() -> Self {
         Self {
@@ -49,60 +73,246 @@ impl AccountState {
         }
     }
 
-    pub fn create_account(&mut self, address: String, balance: u128) -> String {
-        let account = Account { 
-            address: address.clone(), 
-            balance, 
-            strategy_ids: Vec::new(), 
-            limits_orders_long: HashMap::new(), 
-            limits_orders_short: HashMap::new() 
+    /// Fails with `StorageError::Conflict` if `address` already has an
+    /// account. Without this check, re-posting `/create_account` for an
+    /// address that already exists would silently wipe its balance/orders
+    /// and mint fresh API credentials that work as that account -- a
+    /// takeover path this unauthenticated endpoint must not allow.
+    pub fn create_account(&mut self, address: String, balance: u128) -> Result<(String, String), StorageError> {
+        if self.accounts.contains_key(&address) {
+            return Err(StorageError::Conflict(format!("account {}", address)));
+        }
+        let (api_key, api_secret) = generate_api_credentials();
+        let account = Account {
+            address: address.clone(),
+            balance,
+            strategy_ids: Vec::new(),
+            limits_orders_long: HashMap::new(),
+            limits_orders_short: HashMap::new(),
+            api_key: api_key.clone(),
+            api_secret: api_secret.clone(),
         };
-        self.accounts.insert(address.clone(), account);
-        format!("Account created with address: {}", address)
+        self.accounts.insert(address, account);
+        Ok((api_key, api_secret))
     }
 
-    pub fn deposit(&mut self, address: String, amount: u128) -> Result<String, String> {
+    pub fn get_api_secret(&self, api_key: &str) -> Option<String> {
+        self.accounts
+            .values()
+            .find(|account| account.api_key == api_key)
+            .map(|account| account.api_secret.clone())
+    }
+
+    pub fn get_address_for_api_key(&self, api_key: &str) -> Option<String> {
+        self.accounts
+            .values()
+            .find(|account| account.api_key == api_key)
+            .map(|account| account.address.clone())
+    }
+
+    pub fn deposit(&mut self, address: String, amount: u128) -> Result<String, StorageError> {
         match self.accounts.get_mut(&address) {
             Some(account) => {
                 account.balance += amount;
                 Ok(format!("Deposited {} to account {}", amount, address))
             }
-            None => Err(format!("Account {} not found", address))
+            None => Err(StorageError::NotFound(format!("account {}", address)))
         }
     }
 
-    pub fn get_account(&self, address: String) -> Result<Account, String> {
+    pub fn get_account(&self, address: String) -> Result<Account, StorageError> {
         match self.accounts.get(&address) {
             Some(account) => Ok(account.clone()),
-            None => Err(format!("Account {} not found", address))
+            None => Err(StorageError::NotFound(format!("account {}", address)))
         }
     }
 
-    pub fn update_account(&mut self, address: String, new_amount: u128) {
-        let mut account = self.accounts.get_mut(&address).unwrap();
+    pub fn update_account(&mut self, address: String, new_amount: u128) -> Result<(), StorageError> {
+        let account = self.accounts.get_mut(&address)
+            .ok_or_else(|| StorageError::NotFound(format!("account {}", address)))?;
         account.balance = new_amount;
+        Ok(())
     }
 
-    pub fn add_strategy_id(&mut self, address: String, strategy_id: u128) {
-        let mut account = self.accounts.get_mut(&address).unwrap();
+    pub fn add_strategy_id(&mut self, address: String, strategy_id: u128) -> Result<(), StorageError> {
+        let account = self.accounts.get_mut(&address)
+            .ok_or_else(|| StorageError::NotFound(format!("account {}", address)))?;
         account.strategy_ids.push(strategy_id);
+        Ok(())
     }
 
-    pub fn add_limits_order_long(&mut self, address: String, limits_order_long: LimitsOrderLong) -> u128 {
-        let mut account = self.accounts.get_mut(&address).unwrap();
+    pub fn add_limits_order_long(&mut self, address: String, limits_order_long: LimitsOrderLong) -> Result<u128, StorageError> {
         let order_id = self.id_counter;
+        let account = self.accounts.get_mut(&address)
+            .ok_or_else(|| StorageError::NotFound(format!("account {}", address)))?;
         self.id_counter += 1;
         account.limits_orders_long.insert(order_id, limits_order_long);
-        order_id
+        Ok(order_id)
     }
 
-    pub fn get_limits_orders_long(&self, address: String) -> Result<HashMap<u128, LimitsOrderLong>, String> {
+    pub fn get_limits_orders_long(&self, address: String) -> Result<HashMap<u128, LimitsOrderLong>, StorageError> {
         match self.accounts.get(&address) {
             Some(account) => Ok(account.limits_orders_long.clone()),
-            None => Err(format!("Account {} not found", address))
+            None => Err(StorageError::NotFound(format!("account {}", address)))
+        }
+    }
+
+}
+
+/// Homomorphically evaluates every limits order on `asset` against an
+/// incoming, already-encrypted market `price`, without ever decrypting a
+/// threshold or a result. A long triggers on stop-loss (`price <= stop`) or
+/// take-profit (`price >= profit`); a short triggers on the inverse. The two
+/// conditions are OR'd into a single "did this order fire" ciphertext per
+/// order -- only the order's owner, decrypting locally, learns which one (or
+/// whether it fired at all). Caller must `set_server_key` first.
+pub fn evaluate_triggers(account_state: &AccountState, asset: &str, price: &FheUint8) -> HashMap<u128, FheBool> {
+    let mut triggers = HashMap::new();
+
+    for account in account_state.accounts.values() {
+        for (order_id, order) in &account.limits_orders_long {
+            if order.asset != asset {
+                continue;
+            }
+            let stop_loss = price.le(&order.stop);
+            let take_profit = price.ge(&order.profit);
+            triggers.insert(*order_id, stop_loss | take_profit);
+        }
+        for (order_id, order) in &account.limits_orders_short {
+            if order.asset != asset {
+                continue;
+            }
+            let stop_loss = price.ge(&order.stop);
+            let take_profit = price.le(&order.profit);
+            triggers.insert(*order_id, stop_loss | take_profit);
+        }
+    }
+
+    triggers
+}
+
+/// On-disk shape of a `LimitsOrderLong`/`LimitsOrderShort`'s plaintext
+/// fields; its `stop`/`profit` ciphertexts live alongside it in the
+/// account's shared `CompressedCiphertextList` instead.
+#[derive(Serialize, Deserialize)]
+struct PersistedOrderMeta {
+    owner: String,
+    token: String,
+    asset: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedAccount {
+    address: String,
+    balance: u128,
+    strategy_ids: Vec<u128>,
+    api_key: String,
+    api_secret: String,
+    long_orders: Vec<(u128, PersistedOrderMeta)>,
+    short_orders: Vec<(u128, PersistedOrderMeta)>,
+    /// `bincode`-encoded `CompressedCiphertextList` holding, in order,
+    /// `[long[0].stop, long[0].profit, .., short[0].stop, short[0].profit, ..]`.
+    ciphertexts: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedAccounts {
+    id_counter: u128,
+    accounts: Vec<PersistedAccount>,
+}
+
+impl Storage for AccountState {
+    const KEY: &'static str = "accounts";
+
+    fn load(backend: &dyn PersistenceBackend) -> Result<Self, StorageError> {
+        let bytes = backend.read(Self::KEY)?
+            .ok_or_else(|| StorageError::NotFound(Self::KEY.to_string()))?;
+        let persisted: PersistedAccounts = bincode::deserialize(&bytes)
+            .map_err(|e| StorageError::Corrupt(format!("accounts: {}", e)))?;
+
+        let mut accounts = HashMap::new();
+        for persisted_account in persisted.accounts {
+            let compressed: CompressedCiphertextList = bincode::deserialize(&persisted_account.ciphertexts)
+                .map_err(|e| StorageError::Corrupt(format!("ciphertexts for {}: {}", persisted_account.address, e)))?;
+
+            let mut cursor = 0usize;
+            let mut limits_orders_long = HashMap::new();
+            for (order_id, meta) in persisted_account.long_orders {
+                let stop = extract_ciphertext(&compressed, cursor, &persisted_account.address)?;
+                let profit = extract_ciphertext(&compressed, cursor + 1, &persisted_account.address)?;
+                cursor += 2;
+                limits_orders_long.insert(order_id, LimitsOrderLong { owner: meta.owner, token: meta.token, asset: meta.asset, stop, profit });
+            }
+
+            let mut limits_orders_short = HashMap::new();
+            for (order_id, meta) in persisted_account.short_orders {
+                let stop = extract_ciphertext(&compressed, cursor, &persisted_account.address)?;
+                let profit = extract_ciphertext(&compressed, cursor + 1, &persisted_account.address)?;
+                cursor += 2;
+                limits_orders_short.insert(order_id, LimitsOrderShort { owner: meta.owner, token: meta.token, asset: meta.asset, stop, profit });
+            }
+
+            accounts.insert(persisted_account.address.clone(), Account {
+                address: persisted_account.address,
+                balance: persisted_account.balance,
+                strategy_ids: persisted_account.strategy_ids,
+                limits_orders_long,
+                limits_orders_short,
+                api_key: persisted_account.api_key,
+                api_secret: persisted_account.api_secret,
+            });
         }
+
+        Ok(AccountState { id_counter: persisted.id_counter, accounts })
     }
 
+    fn persist(&self, backend: &dyn PersistenceBackend) -> Result<(), StorageError> {
+        let mut persisted_accounts = Vec::with_capacity(self.accounts.len());
+
+        for account in self.accounts.values() {
+            let mut builder = CompressedCiphertextListBuilder::new();
+            let mut long_orders = Vec::with_capacity(account.limits_orders_long.len());
+            let mut short_orders = Vec::with_capacity(account.limits_orders_short.len());
+
+            for (order_id, order) in &account.limits_orders_long {
+                builder.push(order.stop.clone());
+                builder.push(order.profit.clone());
+                long_orders.push((*order_id, PersistedOrderMeta { owner: order.owner.clone(), token: order.token.clone(), asset: order.asset.clone() }));
+            }
+            for (order_id, order) in &account.limits_orders_short {
+                builder.push(order.stop.clone());
+                builder.push(order.profit.clone());
+                short_orders.push((*order_id, PersistedOrderMeta { owner: order.owner.clone(), token: order.token.clone(), asset: order.asset.clone() }));
+            }
+
+            let compressed = builder.build()
+                .map_err(|e| StorageError::Corrupt(format!("compressing ciphertexts for {}: {}", account.address, e)))?;
+            let ciphertexts = bincode::serialize(&compressed)
+                .map_err(|e| StorageError::Corrupt(format!("encoding ciphertexts for {}: {}", account.address, e)))?;
+
+            persisted_accounts.push(PersistedAccount {
+                address: account.address.clone(),
+                balance: account.balance,
+                strategy_ids: account.strategy_ids.clone(),
+                api_key: account.api_key.clone(),
+                api_secret: account.api_secret.clone(),
+                long_orders,
+                short_orders,
+                ciphertexts,
+            });
+        }
+
+        let persisted = PersistedAccounts { id_counter: self.id_counter, accounts: persisted_accounts };
+        let bytes = bincode::serialize(&persisted)
+            .map_err(|e| StorageError::Corrupt(format!("encoding accounts: {}", e)))?;
+        backend.write(Self::KEY, &bytes)
+    }
+}
+
+fn extract_ciphertext(compressed: &CompressedCiphertextList, index: usize, address: &str) -> Result<FheUint8, StorageError> {
+    compressed.get::<FheUint8>(index)
+        .map_err(|e| StorageError::Corrupt(format!("ciphertext {} for {}: {}", index, address, e)))?
+        .ok_or_else(|| StorageError::Corrupt(format!("missing ciphertext {} for {}", index, address)))
 }
 
 #[derive(Deserialize)]
@@ -123,6 +333,13 @@ pub struct GetAccountResponse {
     balance: u128,
 }
 
+#[derive(Serialize)]
+pub struct CreateAccountResponse {
+    address: String,
+    api_key: String,
+    api_secret: String,
+}
+
 #[derive(Deserialize)]
 pub struct InvestRequest {
     address: String,
@@ -130,104 +347,146 @@ pub struct InvestRequest {
     amount: u128,
 }
 
+/// `stop`/`profit` arrive already encrypted under the caller's own
+/// `ClientKey` -- the server never sees the plaintext thresholds.
 #[derive(Deserialize)]
 pub struct AddLimitsOrderLongRequest {
     address: String,
     token: String,
     asset: String,
-    stop: u8,
-    profit: u8,
+    stop: FheUint8,
+    profit: FheUint8,
 }
 
+/// `stop`/`profit` stay ciphertext here too: only the caller, with their own
+/// `ClientKey`, can decrypt them.
 #[derive(Clone, Serialize)]
 pub struct LimitsOrderLongResponse {
     owner: String,
     token: String,
     asset: String,
-    stop: u8,
-    profit: u8,
+    stop: FheUint8,
+    profit: FheUint8,
 }
 
-pub async fn create_account_handler(State(state): State<AppState>, Json(payload): Json<CreateAccountRequest>) -> String {
-    let account = state.account_state.lock().unwrap().create_account(payload.address, payload.balance);
-    format!("Account created: {}", account)
+pub async fn create_account_handler(State(state): State<AppState>, Json(payload): Json<CreateAccountRequest>) -> Result<Json<CreateAccountResponse>, (StatusCode, String)> {
+    let address = payload.address.clone();
+    let mut account_state = state.account_state.lock().unwrap();
+    let (api_key, api_secret) = account_state.create_account(payload.address, payload.balance)
+        .map_err(crate::storage::storage_status)?;
+    account_state.persist(&*state.storage_backend).map_err(crate::storage::storage_status)?;
+    Ok(Json(CreateAccountResponse { address, api_key, api_secret }))
 }
 
-pub async fn deposit_handler(State(state): State<AppState>, Json(payload): Json<DepositRequest>) -> Result<String, (StatusCode, String)> {
-    let mut account_state = state.account_state.lock().unwrap();
-    match account_state.deposit(payload.address, payload.amount) {
-        Ok(message) => Ok(message),
-        Err(error) => Err((StatusCode::NOT_FOUND, error))
+pub async fn deposit_handler(
+    Extension(AuthenticatedAddress(authenticated_address)): Extension<AuthenticatedAddress>,
+    State(state): State<AppState>,
+    Json(payload): Json<DepositRequest>,
+) -> Result<String, (StatusCode, String)> {
+    if payload.address != authenticated_address {
+        return Err((StatusCode::FORBIDDEN, "API key does not authorize this address".to_string()));
     }
+    let mut account_state = state.account_state.lock().unwrap();
+    let message = account_state.deposit(payload.address, payload.amount).map_err(crate::storage::storage_status)?;
+    account_state.persist(&*state.storage_backend).map_err(crate::storage::storage_status)?;
+    Ok(message)
 }
 
 pub async fn get_account_handler(State(state): State<AppState>, Path(address): Path<String>) -> Result<Json<Account>, (StatusCode, String)> {
     let account_state = state.account_state.lock().unwrap();
     match account_state.get_account(address) {
         Ok(account) => Ok(Json(account)),
-        Err(error) => Err((StatusCode::NOT_FOUND, error))
+        Err(error) => Err(crate::storage::storage_status(error))
     }
 }
 
-pub async fn invest_handler(State(state): State<AppState>, Json(payload): Json<InvestRequest>) -> Result<String, (StatusCode, String)> {
+pub async fn invest_handler(
+    Extension(AuthenticatedAddress(authenticated_address)): Extension<AuthenticatedAddress>,
+    State(state): State<AppState>,
+    Json(payload): Json<InvestRequest>,
+) -> Result<String, (StatusCode, String)> {
+    if payload.address != authenticated_address {
+        return Err((StatusCode::FORBIDDEN, "API key does not authorize this address".to_string()));
+    }
     let mut account_state = state.account_state.lock().unwrap();
     let mut trading_state = state.trading_state.lock().unwrap();
     
     // Properly handle the case where account doesn't exist
     let account = match account_state.get_account(payload.address.clone()) {
         Ok(account) => account,
-        Err(error) => return Err((StatusCode::NOT_FOUND, error))
+        Err(error) => return Err(crate::storage::storage_status(error))
     };
     
     if account.balance >= payload.amount {
-        let investor = Investor { address: payload.address.clone(), amount: payload.amount }; 
-        trading_state.add_investor(payload.strategy_id, investor);
-        trading_state.increase_amount(payload.strategy_id, payload.amount);
-        account_state.update_account(payload.address.clone(), account.balance - payload.amount);
-        account_state.add_strategy_id(payload.address.clone(), payload.strategy_id);
+        let investor = Investor { address: payload.address.clone(), amount: payload.amount };
+        trading_state.add_investor(payload.strategy_id, investor).map_err(crate::storage::storage_status)?;
+        trading_state.increase_amount(payload.strategy_id, payload.amount).map_err(crate::storage::storage_status)?;
+        account_state.update_account(payload.address.clone(), account.balance - payload.amount).map_err(crate::storage::storage_status)?;
+        account_state.add_strategy_id(payload.address.clone(), payload.strategy_id).map_err(crate::storage::storage_status)?;
+        account_state.persist(&*state.storage_backend).map_err(crate::storage::storage_status)?;
+        trading_state.persist(&*state.storage_backend).map_err(crate::storage::storage_status)?;
         Ok(format!("Invested {} into strategy {}", payload.amount, payload.strategy_id))
     } else {
         Err((StatusCode::BAD_REQUEST, format!("Insufficient balance")))
     }
 }
 
-pub async fn add_limits_order_long_handler(State(state): State<AppState>, Json(payload): Json<AddLimitsOrderLongRequest>) -> Result<String, (StatusCode, String)> {
+pub async fn add_limits_order_long_handler(
+    Extension(AuthenticatedAddress(authenticated_address)): Extension<AuthenticatedAddress>,
+    State(state): State<AppState>,
+    Json(payload): Json<AddLimitsOrderLongRequest>,
+) -> Result<String, (StatusCode, String)> {
+    if payload.address != authenticated_address {
+        return Err((StatusCode::FORBIDDEN, "API key does not authorize this address".to_string()));
+    }
     let mut account_state = state.account_state.lock().unwrap();
-    let account = account_state.get_account(payload.address.clone());
-    let stop = FheUint8::encrypt(payload.stop, &*state.client_key);
-    let profit = FheUint8::encrypt(payload.profit, &*state.client_key);
-    let limits_order_long = LimitsOrderLong { owner: payload.address.clone(), token: payload.token.clone(), asset: payload.asset.clone(), stop, profit };
-    account_state.add_limits_order_long(payload.address.clone(), limits_order_long);
+    let limits_order_long = LimitsOrderLong { owner: payload.address.clone(), token: payload.token.clone(), asset: payload.asset.clone(), stop: payload.stop, profit: payload.profit };
+    account_state.add_limits_order_long(payload.address.clone(), limits_order_long).map_err(crate::storage::storage_status)?;
+    account_state.persist(&*state.storage_backend).map_err(crate::storage::storage_status)?;
     Ok(format!("Limits order long added"))
 }
 
-pub async fn get_limits_orders_long_handler(State(state): State<AppState>, Path(address): Path<String>) -> Result<Json<HashMap<u128, LimitsOrderLongResponse>>, (StatusCode, String)> {
+pub async fn get_limits_orders_long_handler(
+    Extension(AuthenticatedAddress(authenticated_address)): Extension<AuthenticatedAddress>,
+    State(state): State<AppState>,
+    Path(address): Path<String>,
+) -> Result<Json<HashMap<u128, LimitsOrderLongResponse>>, (StatusCode, String)> {
+    if address != authenticated_address {
+        return Err((StatusCode::FORBIDDEN, "API key does not authorize this address".to_string()));
+    }
     let account_state = state.account_state.lock().unwrap();
     match account_state.get_limits_orders_long(address) {
         Ok(limits_orders_long) => {
-            set_server_key((*state.server_key).clone());
-            let mut decrypted_orders = HashMap::new();
-            
-            for (order_id, order) in limits_orders_long {
-                let decrypted_stop: u8 = order.stop.decrypt(&*state.client_key);
-                let decrypted_profit: u8 = order.profit.decrypt(&*state.client_key);
-                
-                let decrypted_order = LimitsOrderLongResponse {
+            // Ciphertexts pass straight through: only the caller's own
+            // ClientKey can decrypt `stop`/`profit`.
+            let orders = limits_orders_long
+                .into_iter()
+                .map(|(order_id, order)| (order_id, LimitsOrderLongResponse {
                     owner: order.owner,
                     token: order.token,
                     asset: order.asset,
-                    stop: decrypted_stop,
-                    profit: decrypted_profit,
-                };
-                
-                decrypted_orders.insert(order_id, decrypted_order);
-            }
-            
-            Ok(Json(decrypted_orders))
+                    stop: order.stop,
+                    profit: order.profit,
+                }))
+                .collect();
+
+            Ok(Json(orders))
         },
-        Err(error) => Err((StatusCode::NOT_FOUND, error))
+        Err(error) => Err(crate::storage::storage_status(error))
     }
 }
 
+/// `price` arrives already encrypted under the caller's own `ClientKey`.
+#[derive(Deserialize)]
+pub struct EvaluateTriggersRequest {
+    asset: String,
+    price: FheUint8,
+}
+
+pub async fn evaluate_triggers_handler(State(state): State<AppState>, Json(payload): Json<EvaluateTriggersRequest>) -> Json<HashMap<u128, FheBool>> {
+    set_server_key((*state.server_key).clone());
+    let account_state = state.account_state.lock().unwrap();
+    Json(evaluate_triggers(&account_state, &payload.asset, &payload.price))
+}
 
 
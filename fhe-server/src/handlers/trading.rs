@@ -1,12 +1,14 @@
-use tfhe::{FheUint8, ServerKey, set_server_key, ClientKey};
+use tfhe::{CompressedCiphertextList, CompressedCiphertextListBuilder, FheBool, FheUint8, ServerKey, set_server_key};
 use tfhe::prelude::*;
-use axum::{Json, http::StatusCode, extract::{State, Path}};
+use axum::{Json, http::StatusCode, extract::{State, Path}, Extension};
 use serde::{Deserialize, Serialize};
+use crate::auth::AuthenticatedAddress;
+use crate::storage::{PersistenceBackend, Storage, StorageError};
 use crate::AppState;
 use std::collections::HashMap;
 use std::sync::Mutex;
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Investor {
     pub address: String,
     pub amount: u128,
@@ -33,6 +35,12 @@ pub struct TradingState {
     strategies: HashMap<u128, TradingStrategy>,
 }
 
+impl Default for TradingState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TradingState {
     pub fn new() -> Self {
         Self {
@@ -59,43 +67,135 @@ impl TradingState {
         self.strategies.values().cloned().collect()
     }
 
-    pub fn increase_amount(&mut self, id: u128, amount: u128) -> Result<(), String> {
+    pub fn increase_amount(&mut self, id: u128, amount: u128) -> Result<(), StorageError> {
         match self.strategies.get_mut(&id) {
             Some(strategy) => {
                 strategy.amount += amount;
                 Ok(())
             }
-            None => Err(format!("Strategy {} not found", id))
+            None => Err(StorageError::NotFound(format!("strategy {}", id)))
         }
     }
 
-    pub fn add_investor(&mut self, id: u128, investor: Investor) -> Result<(), String> {
+    pub fn add_investor(&mut self, id: u128, investor: Investor) -> Result<(), StorageError> {
         match self.strategies.get_mut(&id) {
             Some(strategy) => {
                 strategy.investors.push(investor);
                 Ok(())
             }
-            None => Err(format!("Strategy {} not found", id))
+            None => Err(StorageError::NotFound(format!("strategy {}", id)))
         }
     }
 
-    pub fn update_strategy_position(&mut self, id: u128, is_long: bool, is_open: bool) -> Result<(), String> {
+    pub fn update_strategy_position(&mut self, id: u128, is_long: bool, is_open: bool) -> Result<(), StorageError> {
         match self.strategies.get_mut(&id) {
             Some(strategy) => {
                 strategy.is_long = is_long;
                 strategy.is_open = is_open;
                 Ok(())
             }
-            None => Err(format!("Strategy {} not found", id))
+            None => Err(StorageError::NotFound(format!("strategy {}", id)))
         }
     }
 }
 
+#[derive(Serialize, Deserialize)]
+struct PersistedStrategyMeta {
+    id: u128,
+    name: String,
+    owner: String,
+    token: String,
+    amount: u128,
+    is_open: bool,
+    is_long: bool,
+    investors: Vec<Investor>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedTradingState {
+    id_counter: u128,
+    strategies: Vec<PersistedStrategyMeta>,
+    /// `bincode`-encoded `CompressedCiphertextList` holding, per strategy in
+    /// the same order as `strategies`, `[upper_bound, lower_bound]`.
+    ciphertexts: Vec<u8>,
+}
+
+impl Storage for TradingState {
+    const KEY: &'static str = "strategies";
+
+    fn load(backend: &dyn PersistenceBackend) -> Result<Self, StorageError> {
+        let bytes = backend.read(Self::KEY)?
+            .ok_or_else(|| StorageError::NotFound(Self::KEY.to_string()))?;
+        let persisted: PersistedTradingState = bincode::deserialize(&bytes)
+            .map_err(|e| StorageError::Corrupt(format!("strategies: {}", e)))?;
+        let compressed: CompressedCiphertextList = bincode::deserialize(&persisted.ciphertexts)
+            .map_err(|e| StorageError::Corrupt(format!("strategy ciphertexts: {}", e)))?;
+
+        let mut strategies = HashMap::new();
+        for (index, meta) in persisted.strategies.into_iter().enumerate() {
+            let upper_bound = extract_ciphertext(&compressed, index * 2, meta.id)?;
+            let lower_bound = extract_ciphertext(&compressed, index * 2 + 1, meta.id)?;
+            strategies.insert(meta.id, TradingStrategy {
+                id: meta.id,
+                name: meta.name,
+                owner: meta.owner,
+                token: meta.token,
+                upper_bound,
+                lower_bound,
+                amount: meta.amount,
+                is_open: meta.is_open,
+                is_long: meta.is_long,
+                investors: meta.investors,
+            });
+        }
+
+        Ok(TradingState { id_counter: persisted.id_counter, strategies })
+    }
+
+    fn persist(&self, backend: &dyn PersistenceBackend) -> Result<(), StorageError> {
+        let mut builder = CompressedCiphertextListBuilder::new();
+        let mut strategies = Vec::with_capacity(self.strategies.len());
+
+        for strategy in self.strategies.values() {
+            builder.push(strategy.upper_bound.clone());
+            builder.push(strategy.lower_bound.clone());
+            strategies.push(PersistedStrategyMeta {
+                id: strategy.id,
+                name: strategy.name.clone(),
+                owner: strategy.owner.clone(),
+                token: strategy.token.clone(),
+                amount: strategy.amount,
+                is_open: strategy.is_open,
+                is_long: strategy.is_long,
+                investors: strategy.investors.clone(),
+            });
+        }
+
+        let compressed = builder.build()
+            .map_err(|e| StorageError::Corrupt(format!("compressing strategy ciphertexts: {}", e)))?;
+        let ciphertexts = bincode::serialize(&compressed)
+            .map_err(|e| StorageError::Corrupt(format!("encoding strategy ciphertexts: {}", e)))?;
+
+        let persisted = PersistedTradingState { id_counter: self.id_counter, strategies, ciphertexts };
+        let bytes = bincode::serialize(&persisted)
+            .map_err(|e| StorageError::Corrupt(format!("encoding strategies: {}", e)))?;
+        backend.write(Self::KEY, &bytes)
+    }
+}
+
+fn extract_ciphertext(compressed: &CompressedCiphertextList, index: usize, strategy_id: u128) -> Result<FheUint8, StorageError> {
+    compressed.get::<FheUint8>(index)
+        .map_err(|e| StorageError::Corrupt(format!("ciphertext {} for strategy {}: {}", index, strategy_id, e)))?
+        .ok_or_else(|| StorageError::Corrupt(format!("missing ciphertext {} for strategy {}", index, strategy_id)))
+}
+
+/// `upper_bound`/`lower_bound` arrive already encrypted under the caller's
+/// own `ClientKey` -- the server never sees the plaintext threshold.
 #[derive(Deserialize)]
 pub struct CreateStrategyRequest {
     name: String,
-    upper_bound: u8,
-    lower_bound: u8,
+    upper_bound: FheUint8,
+    lower_bound: FheUint8,
     owner: String,
     token: String,
 }
@@ -112,16 +212,40 @@ pub struct GetStrategyResponse {
     investors: Vec<Investor>,
 }
 
+impl From<TradingStrategy> for GetStrategyResponse {
+    fn from(strategy: TradingStrategy) -> Self {
+        Self {
+            id: strategy.id,
+            name: strategy.name,
+            owner: strategy.owner,
+            token: strategy.token,
+            amount: strategy.amount,
+            is_open: strategy.is_open,
+            is_long: strategy.is_long,
+            investors: strategy.investors,
+        }
+    }
+}
+
+/// `value` arrives pre-encrypted under the caller's own `ClientKey`.
 #[derive(Deserialize)]
 pub struct CheckLongStrategyRequest {
     strategy_id: u128,
-    value: u8,
+    value: FheUint8,
 }
 
+/// `value` arrives pre-encrypted under the caller's own `ClientKey`.
 #[derive(Deserialize)]
 pub struct CheckShortStrategyRequest {
     strategy_id: u128,
-    value: u8,
+    value: FheUint8,
+}
+
+/// An encrypted `bool` the caller must decrypt with their own `ClientKey`.
+/// The server never learns the comparison result.
+#[derive(Serialize)]
+pub struct EncryptedBoolResponse {
+    result: FheBool,
 }
 
 #[derive(Deserialize)]
@@ -135,92 +259,84 @@ pub struct CalcRsiRequest {
     prices: Vec<f64>,
 }
 
-pub async fn create_strategy_handler(State(state): State<AppState>, Json(payload): Json<CreateStrategyRequest>) -> Result<String, (StatusCode, String)> {
-    let upper_bound = FheUint8::encrypt(payload.upper_bound, &*state.client_key);
-    let lower_bound = FheUint8::encrypt(payload.lower_bound, &*state.client_key);
+pub async fn create_strategy_handler(
+    Extension(AuthenticatedAddress(authenticated_address)): Extension<AuthenticatedAddress>,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateStrategyRequest>,
+) -> Result<String, (StatusCode, String)> {
+    if payload.owner != authenticated_address {
+        return Err((StatusCode::FORBIDDEN, "API key does not authorize this owner".to_string()));
+    }
     let name = payload.name.clone();
-    let strategy_id = state.trading_state.lock().unwrap().create_strategy(payload.name, payload.owner, upper_bound, lower_bound, payload.token);
+    let mut trading_state = state.trading_state.lock().unwrap();
+    let strategy_id = trading_state.create_strategy(payload.name, payload.owner, payload.upper_bound, payload.lower_bound, payload.token);
+    trading_state.persist(&*state.storage_backend).map_err(crate::storage::storage_status)?;
     Ok(format!("Strategy created: {} with ID: {}", name, strategy_id))
 }
 
 pub async fn get_strategy_handler(State(state): State<AppState>, Path(id): Path<u128>) -> Result<Json<GetStrategyResponse>, (StatusCode, String)> {
     let trading_state = state.trading_state.lock().unwrap();
     match trading_state.get_strategy(id) {
-        Ok(strategy) => Ok(Json(GetStrategyResponse {
-            id: strategy.id,
-            name: strategy.name,
-            owner: strategy.owner,  
-            token: strategy.token,
-            amount: strategy.amount,
-            is_open: strategy.is_open,
-            is_long: strategy.is_long,
-            investors: strategy.investors,
-        })),
+        Ok(strategy) => Ok(Json(strategy.into())),
         Err(error) => Err((StatusCode::NOT_FOUND, error))
     }
 }
 
 pub async fn get_all_strategies_handler(State(state): State<AppState>) -> Json<Vec<GetStrategyResponse>> {
     let strategies = state.trading_state.lock().unwrap().get_all_strategies();
-    Json(strategies.into_iter().map(|strategy| GetStrategyResponse {
-        id: strategy.id,
-        name: strategy.name,
-        owner: strategy.owner,
-        token: strategy.token,
-        amount: strategy.amount,
-        is_open: strategy.is_open,
-        is_long: strategy.is_long,
-        investors: strategy.investors,
-    }).collect())
+    Json(strategies.into_iter().map(GetStrategyResponse::from).collect())
 }
 
-pub async fn check_long_strategy_handler(State(state): State<AppState>, Json(payload): Json<CheckLongStrategyRequest>) -> Result<String, (StatusCode, String)> {
+pub async fn check_long_strategy_handler(State(state): State<AppState>, Json(payload): Json<CheckLongStrategyRequest>) -> Result<Json<EncryptedBoolResponse>, (StatusCode, String)> {
     let trading_state = state.trading_state.lock().unwrap();
     let strategy = match trading_state.get_strategy(payload.strategy_id) {
         Ok(strategy) => strategy,
         Err(error) => return Err((StatusCode::NOT_FOUND, error))
     };
-    
-    let lower_bound = strategy.lower_bound;
+
     set_server_key((*state.server_key).clone());
-    let value = FheUint8::encrypt(payload.value, &*state.client_key);
-    let result = lower_bound.gt(&value);
-    let result_decrypted: bool = result.decrypt(&*state.client_key);
-    Ok(format!("Result: {}", result_decrypted))
+    let result = strategy.lower_bound.gt(&payload.value);
+    Ok(Json(EncryptedBoolResponse { result }))
 }
 
-pub async fn check_short_strategy_handler(State(state): State<AppState>, Json(payload): Json<CheckShortStrategyRequest>) -> Result<String, (StatusCode, String)> {
+pub async fn check_short_strategy_handler(State(state): State<AppState>, Json(payload): Json<CheckShortStrategyRequest>) -> Result<Json<EncryptedBoolResponse>, (StatusCode, String)> {
     let trading_state = state.trading_state.lock().unwrap();
     let strategy = match trading_state.get_strategy(payload.strategy_id) {
         Ok(strategy) => strategy,
         Err(error) => return Err((StatusCode::NOT_FOUND, error))
     };
-    
-    let upper_bound = strategy.upper_bound;
+
     set_server_key((*state.server_key).clone());
-    let value = FheUint8::encrypt(payload.value, &*state.client_key);
-    let result = upper_bound.lt(&value);
-    let result_decrypted: bool = result.decrypt(&*state.client_key);
-    Ok(format!("Result: {}", result_decrypted))
+    let result = strategy.upper_bound.lt(&payload.value);
+    Ok(Json(EncryptedBoolResponse { result }))
 }
 
-pub async fn open_trade_handler(State(state): State<AppState>, Json(payload): Json<OpenTradeRequest>) -> Result<String, (StatusCode, String)> {
+pub async fn open_trade_handler(
+    Extension(AuthenticatedAddress(authenticated_address)): Extension<AuthenticatedAddress>,
+    State(state): State<AppState>,
+    Json(payload): Json<OpenTradeRequest>,
+) -> Result<String, (StatusCode, String)> {
     let mut trading_state = state.trading_state.lock().unwrap();
-    let strategy = match trading_state.get_strategy(payload.strategy_id) {
-        Ok(strategy) => strategy,
-        Err(error) => return Err((StatusCode::NOT_FOUND, error))
-    };
-    trading_state.update_strategy_position(payload.strategy_id, payload.is_long, true);
+    let strategy = trading_state.get_strategy(payload.strategy_id)
+        .map_err(|error| (StatusCode::NOT_FOUND, error))?;
+    if strategy.owner != authenticated_address {
+        return Err((StatusCode::FORBIDDEN, "API key does not authorize this strategy".to_string()));
+    }
+    trading_state.update_strategy_position(payload.strategy_id, payload.is_long, true)
+        .map_err(crate::storage::storage_status)?;
+    trading_state.persist(&*state.storage_backend).map_err(crate::storage::storage_status)?;
     Ok(format!("Trade opened"))
 }
 
 pub async fn calc_rsi(State(_state): State<AppState>, Json(payload): Json<CalcRsiRequest>) -> Result<Json<u8>, (StatusCode, String)> {
-    if payload.prices.len() < 15 {
+    Ok(Json(compute_rsi_u8(&payload.prices)?))
+}
+
+pub(crate) fn compute_rsi_u8(prices: &[f64]) -> Result<u8, (StatusCode, String)> {
+    if prices.len() < 15 {
         return Err((StatusCode::BAD_REQUEST, "Need at least 15 price values for RSI calculation".to_string()));
     }
 
-    let prices = &payload.prices;
-    
     // Calculate RSI using the standard 14-period Wilder RSI
     let rsi_value = match calculate_rsi(prices) {
         Some(rsi) => rsi,
@@ -228,9 +344,35 @@ pub async fn calc_rsi(State(_state): State<AppState>, Json(payload): Json<CalcRs
     };
 
     // Convert to u8 (0-100 range) and clamp
-    let rsi_u8 = (rsi_value.round() as u8).min(100);
-    
-    Ok(Json(rsi_u8))
+    Ok((rsi_value.round() as u8).min(100))
+}
+
+/// Homomorphically checks every strategy's encrypted `[lower_bound,
+/// upper_bound]` band against the latest RSI reading. `rsi` is already
+/// public (derived from public price history by `compute_rsi_u8`), so it's
+/// lifted into the ciphertext domain with a trivial encryption -- no
+/// `ClientKey` needed for that -- but the bracket test itself still runs
+/// entirely under FHE, so the server never learns which strategies are in
+/// bracket. This stays a manual, two-step flow by necessity: since the
+/// server holds no `ClientKey` (see chunk0-3), it cannot decrypt the result
+/// to auto-flag a strategy itself. Only the strategy owner, decrypting the
+/// returned ciphertext locally, learns whether to act -- and if so, calls
+/// `open_trade_handler` themselves to flip `update_strategy_position`.
+pub fn evaluate_rsi_brackets(trading_state: &TradingState, rsi: u8) -> HashMap<u128, FheBool> {
+    let rsi_ciphertext = FheUint8::encrypt_trivial(rsi);
+    trading_state.strategies.values()
+        .map(|strategy| {
+            let bracketed = strategy.lower_bound.le(&rsi_ciphertext) & strategy.upper_bound.ge(&rsi_ciphertext);
+            (strategy.id, bracketed)
+        })
+        .collect()
+}
+
+pub async fn evaluate_rsi_brackets_handler(State(state): State<AppState>, Json(payload): Json<CalcRsiRequest>) -> Result<Json<HashMap<u128, FheBool>>, (StatusCode, String)> {
+    let rsi = compute_rsi_u8(&payload.prices)?;
+    set_server_key((*state.server_key).clone());
+    let trading_state = state.trading_state.lock().unwrap();
+    Ok(Json(evaluate_rsi_brackets(&trading_state, rsi)))
 }
 
 /// Standard 14-period Wilder RSI.
@@ -4,95 +4,127 @@ use axum::{
     middleware::{self, Next},
     response::IntoResponse,
 };
+use std::path::PathBuf;
 use std::sync::Arc;
-use tfhe::{
-    FheUint8,
-    CompressedCiphertextListBuilder,
-    set_server_key,
-};
+use tfhe::set_server_key;
 use tfhe::prelude::*;
-use tfhe::{ServerKey, ClientKey};
+use tfhe::ServerKey;
+mod auth;
 mod fhe;
 mod handlers;
+mod rpc;
+mod storage;
 use crate::handlers::account::AccountState;
+use crate::storage::{FileBackend, PersistenceBackend, Storage, StorageError};
 use handlers::trading::TradingState;
 use serde::Deserialize;
 use std::sync::Mutex;
 
 
+// The server only ever holds a `ServerKey`. There is no `ClientKey` here, by
+// design: clients generate their own keypair, encrypt thresholds/values
+// locally, and decrypt whatever ciphertext the server hands back. The server
+// can homomorphically evaluate requests but can never see a plaintext
+// stop/profit/bound/value.
 #[derive(Clone)]
 struct AppState {
     server_key: Arc<ServerKey>,
-    client_key: Arc<ClientKey>,
     trading_state: Arc<Mutex<TradingState>>,
     account_state: Arc<Mutex<AccountState>>,
+    storage_backend: Arc<dyn PersistenceBackend>,
+}
+
+/// Loads `T` from `backend`, treating a cold start (nothing persisted yet)
+/// as `Default`. Corruption is never swallowed: a `StorageError::Corrupt`
+/// here means the on-disk state can't be trusted and the server should not
+/// start up silently forgetting it.
+fn load_or_default<T: Storage + Default>(backend: &dyn PersistenceBackend) -> T {
+    match T::load(backend) {
+        Ok(state) => state,
+        Err(StorageError::NotFound(_)) => T::default(),
+        Err(err) => panic!("failed to load persisted state under {:?}: {}", T::KEY, err),
+    }
 }
 
 pub trait KeyAccess {
     fn get_server_key(&self) -> Arc<ServerKey>;
-    fn get_client_key(&self) -> Arc<ClientKey>;
 }
 
 impl KeyAccess for AppState {
     fn get_server_key(&self) -> Arc<ServerKey> {
         self.server_key.clone()
     }
-    fn get_client_key(&self) -> Arc<ClientKey> {
-        self.client_key.clone()
-    }
 }
 
 
-// Simple hello world handler
-async fn hello_world(State(state): State<AppState>) -> String {
-    set_server_key((*state.server_key).clone());
-    let a = FheUint8::encrypt(10 as u8, &*state.client_key);
-    let b = FheUint8::encrypt(5 as u8, &*state.client_key);
-    let c = a + b;
-    let decrypted: u8 = c.decrypt(&*state.client_key);
-    println!("decrypted: {}", decrypted);
-    format!("Hello, FHE World! The decrypted result is: {}", decrypted)
+// Simple liveness handler. It used to homomorphically add two values and
+// decrypt the result to prove FHE was wired up, but that required the
+// server to hold a `ClientKey` -- which is exactly what this server must
+// never have.
+async fn hello_world() -> &'static str {
+    "Hello, FHE World!"
 }
 
 
 #[tokio::main]
 async fn main() {
 
-    if let Err(e) = fhe::key_gen::generate_and_save_keys() {
-        eprintln!("Failed to generate keys: {}", e);
+    if let Err(e) = fhe::key_gen::generate_and_save_server_key() {
+        eprintln!("Failed to generate server key: {}", e);
         return;
     }
 
-    let trading_state = TradingState::new();
-    let account_state = AccountState::new();
+    let storage_backend: Arc<dyn PersistenceBackend> = Arc::new(
+        FileBackend::new(PathBuf::from("data")).expect("failed to initialize storage directory"),
+    );
+
+    let trading_state: TradingState = load_or_default(&*storage_backend);
+    let account_state: AccountState = load_or_default(&*storage_backend);
 
-    let state = AppState { 
+    let state = AppState {
         server_key: Arc::new(fhe::key_gen::load_server_key().unwrap()),
-        client_key: Arc::new(fhe::key_gen::load_client_key().unwrap()),
         trading_state: Arc::new(Mutex::new(trading_state)),
         account_state: Arc::new(Mutex::new(account_state)),
+        storage_backend,
     };
 
-<<<<<<< HEAD
-=======
-
-    // We'll use our own CORS middleware
-
->>>>>>> 249f71bbb52518528442d22755da4e3e51724abf
-    let app = Router::new()
+    // Routes that don't require a signed request: account creation hands out
+    // the API key/secret a client needs to sign everything else, and reading
+    // a strategy/account by id is not sensitive enough to gate.
+    let public_routes = Router::new()
         .route("/", get(hello_world))
-        .route("/create_strategy", post(handlers::trading::create_strategy_handler))
+        .route("/create_account", post(handlers::account::create_account_handler))
+        .route("/get_account/:address", get(handlers::account::get_account_handler))
         .route("/check_long_strategy", post(handlers::trading::check_long_strategy_handler))
         .route("/check_short_strategy", post(handlers::trading::check_short_strategy_handler))
         .route("/get_strategy/:id", get(handlers::trading::get_strategy_handler))
         .route("/get_all_strategies", get(handlers::trading::get_all_strategies_handler))
-<<<<<<< HEAD
-        .route("/create_account", post(handlers::account::create_account_handler))
+        .route("/calc_rsi", post(handlers::trading::calc_rsi))
+        .route("/rpc", post(rpc::rpc_handler));
+
+    // Routes that change a specific account/strategy or list an account's
+    // limits orders (`get_limits_orders_long`), and routes that run
+    // an unbounded homomorphic comparison over every account/strategy, must
+    // carry a valid X-Api-Key/X-Timestamp/X-Signature via
+    // `auth::auth_middleware`. `evaluate_triggers`/`evaluate_rsi_brackets`
+    // aren't bound to the caller's own address -- they scan every
+    // account/strategy by design -- but still require a registered, signed
+    // caller so their per-entry FHE comparisons aren't a free DoS lever for
+    // anonymous callers.
+    let authenticated_routes = Router::new()
         .route("/deposit", post(handlers::account::deposit_handler))
-        .route("/get_account/:address", get(handlers::account::get_account_handler))
-=======
+        .route("/invest", post(handlers::account::invest_handler))
+        .route("/add_limits_order_long", post(handlers::account::add_limits_order_long_handler))
+        .route("/get_limits_orders_long/:address", get(handlers::account::get_limits_orders_long_handler))
+        .route("/create_strategy", post(handlers::trading::create_strategy_handler))
+        .route("/open_trade", post(handlers::trading::open_trade_handler))
+        .route("/evaluate_triggers", post(handlers::account::evaluate_triggers_handler))
+        .route("/evaluate_rsi_brackets", post(handlers::trading::evaluate_rsi_brackets_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::auth_middleware));
+
+    let app = public_routes
+        .merge(authenticated_routes)
         .layer(middleware::from_fn(cors_middleware))
->>>>>>> 249f71bbb52518528442d22755da4e3e51724abf
         .with_state(state);
 
     
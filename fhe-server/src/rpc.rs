@@ -0,0 +1,358 @@
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tfhe::prelude::*;
+use tfhe::set_server_key;
+use tfhe::FheUint8;
+
+use crate::auth;
+use crate::handlers::account::{self, Account, LimitsOrderLong};
+use crate::handlers::trading::{self, GetStrategyResponse, Investor};
+use crate::storage::{Storage, StorageError};
+use crate::AppState;
+
+// Standard JSON-RPC 2.0 error codes (https://www.jsonrpc.org/specification#error_object).
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Implementation-defined server errors live in [-32099, -32000].
+const NOT_FOUND: i64 = -32000;
+const CORRUPT_STATE: i64 = -32001;
+const INSUFFICIENT_BALANCE: i64 = -32002;
+const CONFLICT: i64 = -32003;
+const UNAUTHORIZED: i64 = -32004;
+const FORBIDDEN: i64 = -32005;
+
+#[derive(Deserialize)]
+struct JsonRpcRequest {
+    #[serde(default)]
+    jsonrpc: Option<String>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    id: Value,
+}
+
+#[derive(Serialize)]
+struct JsonRpcResponse {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonRpcError>,
+    id: Value,
+}
+
+#[derive(Serialize)]
+struct JsonRpcError {
+    code: i64,
+    message: String,
+}
+
+impl JsonRpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+impl From<StorageError> for JsonRpcError {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::NotFound(what) => JsonRpcError::new(NOT_FOUND, format!("not found: {}", what)),
+            StorageError::Corrupt(what) => JsonRpcError::new(CORRUPT_STATE, format!("corrupt persisted state: {}", what)),
+            StorageError::Io(what) => JsonRpcError::new(CORRUPT_STATE, format!("storage io error: {}", what)),
+            StorageError::Conflict(what) => JsonRpcError::new(CONFLICT, format!("already exists: {}", what)),
+        }
+    }
+}
+
+/// Single JSON-RPC 2.0 entry point mirroring the REST handlers in
+/// `handlers::account`/`handlers::trading`. Accepts either one request
+/// object or a batch (a JSON array) and answers with the same shape back --
+/// one response object, or one array of responses in request order.
+///
+/// Just like the REST routes, some methods (`deposit`, `invest`,
+/// `add_limits_order_long`, `get_limits_orders_long`, `create_strategy`,
+/// `open_trade`, `evaluate_triggers`, `evaluate_rsi_brackets`) require a
+/// valid `X-Api-Key`/`X-Timestamp`/`X-Signature` over the whole HTTP
+/// request -- `dispatch` checks that signature with
+/// [`auth::verify_signature`] the same way `auth::auth_middleware` does for
+/// REST, and binds the signer's address to the method's `address`/`owner`
+/// param before touching any state. The read-only methods (`get_account`,
+/// `get_strategy`, `get_all_strategies`, `check_*_strategy`, `calc_rsi`)
+/// and `create_account` need no signature, matching their REST
+/// counterparts in `public_routes`.
+pub async fn rpc_handler(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Json<Value> {
+    let parsed: Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(err) => return Json(error_response(Value::Null, JsonRpcError::new(PARSE_ERROR, err.to_string()))),
+    };
+
+    match parsed {
+        Value::Array(requests) => {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.push(handle_one(&state, &headers, &body, request).await);
+            }
+            Json(Value::Array(responses))
+        }
+        single => Json(handle_one(&state, &headers, &body, single).await),
+    }
+}
+
+async fn handle_one(state: &AppState, headers: &HeaderMap, body: &[u8], raw: Value) -> Value {
+    let id = raw.get("id").cloned().unwrap_or(Value::Null);
+
+    let request: JsonRpcRequest = match serde_json::from_value(raw) {
+        Ok(request) => request,
+        Err(err) => return error_response(id, JsonRpcError::new(INVALID_REQUEST, err.to_string())),
+    };
+
+    if request.jsonrpc.as_deref() != Some("2.0") {
+        return error_response(request.id, JsonRpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""));
+    }
+
+    match dispatch(state, headers, body, &request.method, request.params).await {
+        Ok(result) => envelope(request.id, Some(result), None),
+        Err(error) => envelope(request.id, None, Some(error)),
+    }
+}
+
+/// Verifies the request's HMAC signature and requires the signer's address
+/// to match `expected_address` (an `address`/`owner` param a method was
+/// called with), mirroring the `authenticated_address` check the
+/// address-bound REST handlers do.
+fn authorize(state: &AppState, headers: &HeaderMap, body: &[u8], expected_address: &str) -> Result<(), JsonRpcError> {
+    let address = auth::verify_signature(state, headers, "POST", "/rpc", body)
+        .map_err(|(_, message)| JsonRpcError::new(UNAUTHORIZED, message))?;
+    if address != expected_address {
+        return Err(JsonRpcError::new(FORBIDDEN, "API key does not authorize this address"));
+    }
+    Ok(())
+}
+
+/// Verifies the request's HMAC signature without binding it to a specific
+/// address -- for methods like `evaluate_triggers`/`evaluate_rsi_brackets`
+/// that scan every account/strategy rather than acting on the caller's own.
+fn require_signed(state: &AppState, headers: &HeaderMap, body: &[u8]) -> Result<(), JsonRpcError> {
+    auth::verify_signature(state, headers, "POST", "/rpc", body)
+        .map_err(|(_, message)| JsonRpcError::new(UNAUTHORIZED, message))?;
+    Ok(())
+}
+
+fn error_response(id: Value, error: JsonRpcError) -> Value {
+    envelope(id, None, Some(error))
+}
+
+fn envelope(id: Value, result: Option<Value>, error: Option<JsonRpcError>) -> Value {
+    serde_json::to_value(JsonRpcResponse { jsonrpc: "2.0", result, error, id })
+        .expect("JsonRpcResponse is always serializable")
+}
+
+fn parse<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, JsonRpcError> {
+    serde_json::from_value(params).map_err(|err| JsonRpcError::new(INVALID_PARAMS, format!("invalid params: {}", err)))
+}
+
+#[derive(Deserialize)]
+struct CreateAccountParams {
+    address: String,
+    balance: u128,
+}
+
+#[derive(Deserialize)]
+struct DepositParams {
+    address: String,
+    amount: u128,
+}
+
+#[derive(Deserialize)]
+struct InvestParams {
+    address: String,
+    strategy_id: u128,
+    amount: u128,
+}
+
+#[derive(Deserialize)]
+struct AddLimitsOrderLongParams {
+    address: String,
+    token: String,
+    asset: String,
+    stop: FheUint8,
+    profit: FheUint8,
+}
+
+#[derive(Deserialize)]
+struct EvaluateTriggersParams {
+    asset: String,
+    price: FheUint8,
+}
+
+#[derive(Deserialize)]
+struct CreateStrategyParams {
+    name: String,
+    owner: String,
+    token: String,
+    upper_bound: FheUint8,
+    lower_bound: FheUint8,
+}
+
+#[derive(Deserialize)]
+struct CheckStrategyParams {
+    strategy_id: u128,
+    value: FheUint8,
+}
+
+#[derive(Deserialize)]
+struct OpenTradeParams {
+    strategy_id: u128,
+    is_long: bool,
+}
+
+#[derive(Deserialize)]
+struct CalcRsiParams {
+    prices: Vec<f64>,
+}
+
+async fn dispatch(state: &AppState, headers: &HeaderMap, body: &[u8], method: &str, params: Value) -> Result<Value, JsonRpcError> {
+    match method {
+        "create_account" => {
+            let p: CreateAccountParams = parse(params)?;
+            let mut account_state = state.account_state.lock().unwrap();
+            let (api_key, api_secret) = account_state.create_account(p.address.clone(), p.balance)?;
+            account_state.persist(&*state.storage_backend)?;
+            Ok(json!({ "address": p.address, "api_key": api_key, "api_secret": api_secret }))
+        }
+        "get_account" => {
+            let address: String = parse(params)?;
+            let account_state = state.account_state.lock().unwrap();
+            let account: Account = account_state.get_account(address)?;
+            Ok(serde_json::to_value(account).expect("Account is always serializable"))
+        }
+        "deposit" => {
+            let p: DepositParams = parse(params)?;
+            authorize(state, headers, body, &p.address)?;
+            let mut account_state = state.account_state.lock().unwrap();
+            let message = account_state.deposit(p.address, p.amount)?;
+            account_state.persist(&*state.storage_backend)?;
+            Ok(json!(message))
+        }
+        "invest" => {
+            let p: InvestParams = parse(params)?;
+            authorize(state, headers, body, &p.address)?;
+            let mut account_state = state.account_state.lock().unwrap();
+            let mut trading_state = state.trading_state.lock().unwrap();
+            let account = account_state.get_account(p.address.clone())?;
+            if account.balance() < p.amount {
+                return Err(JsonRpcError::new(INSUFFICIENT_BALANCE, "Insufficient balance"));
+            }
+            let investor = Investor { address: p.address.clone(), amount: p.amount };
+            trading_state.add_investor(p.strategy_id, investor)?;
+            trading_state.increase_amount(p.strategy_id, p.amount)?;
+            account_state.update_account(p.address.clone(), account.balance() - p.amount)?;
+            account_state.add_strategy_id(p.address.clone(), p.strategy_id)?;
+            account_state.persist(&*state.storage_backend)?;
+            trading_state.persist(&*state.storage_backend)?;
+            Ok(json!(format!("Invested {} into strategy {}", p.amount, p.strategy_id)))
+        }
+        "add_limits_order_long" => {
+            let p: AddLimitsOrderLongParams = parse(params)?;
+            authorize(state, headers, body, &p.address)?;
+            let mut account_state = state.account_state.lock().unwrap();
+            let order = LimitsOrderLong::new(p.address.clone(), p.token, p.asset, p.stop, p.profit);
+            let order_id = account_state.add_limits_order_long(p.address, order)?;
+            account_state.persist(&*state.storage_backend)?;
+            Ok(json!({ "order_id": order_id }))
+        }
+        "get_limits_orders_long" => {
+            let address: String = parse(params)?;
+            authorize(state, headers, body, &address)?;
+            let account_state = state.account_state.lock().unwrap();
+            let orders = account_state.get_limits_orders_long(address)?;
+            Ok(serde_json::to_value(orders).expect("orders are always serializable"))
+        }
+        "evaluate_triggers" => {
+            let p: EvaluateTriggersParams = parse(params)?;
+            require_signed(state, headers, body)?;
+            set_server_key((*state.server_key).clone());
+            let account_state = state.account_state.lock().unwrap();
+            let triggers = account::evaluate_triggers(&account_state, &p.asset, &p.price);
+            Ok(serde_json::to_value(triggers).expect("triggers are always serializable"))
+        }
+        "create_strategy" => {
+            let p: CreateStrategyParams = parse(params)?;
+            authorize(state, headers, body, &p.owner)?;
+            let mut trading_state = state.trading_state.lock().unwrap();
+            let strategy_id = trading_state.create_strategy(p.name, p.owner, p.upper_bound, p.lower_bound, p.token);
+            trading_state.persist(&*state.storage_backend)?;
+            Ok(json!({ "strategy_id": strategy_id }))
+        }
+        "get_strategy" => {
+            let id: u128 = parse(params)?;
+            let trading_state = state.trading_state.lock().unwrap();
+            let strategy = trading_state.get_strategy(id).map_err(|e| JsonRpcError::new(NOT_FOUND, e))?;
+            let response: GetStrategyResponse = strategy.into();
+            Ok(serde_json::to_value(response).expect("strategy is always serializable"))
+        }
+        "get_all_strategies" => {
+            let trading_state = state.trading_state.lock().unwrap();
+            let strategies: Vec<GetStrategyResponse> =
+                trading_state.get_all_strategies().into_iter().map(GetStrategyResponse::from).collect();
+            Ok(serde_json::to_value(strategies).expect("strategies are always serializable"))
+        }
+        "check_long_strategy" => {
+            let p: CheckStrategyParams = parse(params)?;
+            let trading_state = state.trading_state.lock().unwrap();
+            let strategy = trading_state.get_strategy(p.strategy_id).map_err(|e| JsonRpcError::new(NOT_FOUND, e))?;
+            set_server_key((*state.server_key).clone());
+            let result = strategy.lower_bound.gt(&p.value);
+            Ok(json!({ "result": result }))
+        }
+        "check_short_strategy" => {
+            let p: CheckStrategyParams = parse(params)?;
+            let trading_state = state.trading_state.lock().unwrap();
+            let strategy = trading_state.get_strategy(p.strategy_id).map_err(|e| JsonRpcError::new(NOT_FOUND, e))?;
+            set_server_key((*state.server_key).clone());
+            let result = strategy.upper_bound.lt(&p.value);
+            Ok(json!({ "result": result }))
+        }
+        "open_trade" => {
+            let p: OpenTradeParams = parse(params)?;
+            // Look up the owner and drop the `trading_state` guard before
+            // calling `authorize` (which locks `account_state`): every other
+            // authenticated arm in this file locks account_state before
+            // trading_state (or authorizes before taking any lock), and
+            // holding trading_state across that call here would invert the
+            // order -- a concurrent "invest" (account_state -> trading_state)
+            // could deadlock against this arm (trading_state -> account_state).
+            let owner = {
+                let trading_state = state.trading_state.lock().unwrap();
+                trading_state.get_strategy(p.strategy_id).map_err(|e| JsonRpcError::new(NOT_FOUND, e))?.owner
+            };
+            authorize(state, headers, body, &owner)?;
+            let mut trading_state = state.trading_state.lock().unwrap();
+            trading_state.update_strategy_position(p.strategy_id, p.is_long, true)?;
+            trading_state.persist(&*state.storage_backend)?;
+            Ok(json!("Trade opened"))
+        }
+        "calc_rsi" => {
+            let p: CalcRsiParams = parse(params)?;
+            let rsi = trading::compute_rsi_u8(&p.prices).map_err(|(_, message)| JsonRpcError::new(INVALID_PARAMS, message))?;
+            Ok(json!(rsi))
+        }
+        "evaluate_rsi_brackets" => {
+            let p: CalcRsiParams = parse(params)?;
+            require_signed(state, headers, body)?;
+            let rsi = trading::compute_rsi_u8(&p.prices).map_err(|(_, message)| JsonRpcError::new(INVALID_PARAMS, message))?;
+            set_server_key((*state.server_key).clone());
+            let trading_state = state.trading_state.lock().unwrap();
+            let brackets = trading::evaluate_rsi_brackets(&trading_state, rsi);
+            Ok(serde_json::to_value(brackets).expect("brackets are always serializable"))
+        }
+        _ => Err(JsonRpcError::new(METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+    }
+}
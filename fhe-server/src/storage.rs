@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use axum::http::StatusCode;
+
+/// Errors that can occur while loading or persisting server state.
+///
+/// `Corrupt` is kept distinct from `NotFound` and `Io` so callers can tell
+/// "nothing is there yet" / "disk problem" apart from "something is there but we can't
+/// trust it" (e.g. a ciphertext that fails to deserialize), which should
+/// fail loudly instead of silently falling back to an empty state.
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound(String),
+    Corrupt(String),
+    Io(String),
+    Conflict(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound(what) => write!(f, "not found: {}", what),
+            StorageError::Corrupt(what) => write!(f, "corrupt persisted state: {}", what),
+            StorageError::Io(what) => write!(f, "storage io error: {}", what),
+            StorageError::Conflict(what) => write!(f, "already exists: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Io(err.to_string())
+    }
+}
+
+/// Maps a `StorageError` to the `(StatusCode, String)` pair handlers already
+/// return for domain errors, so callers can just `.map_err(storage_status)?`.
+pub fn storage_status(err: StorageError) -> (StatusCode, String) {
+    match err {
+        StorageError::NotFound(what) => (StatusCode::NOT_FOUND, format!("not found: {}", what)),
+        StorageError::Corrupt(what) => (StatusCode::INTERNAL_SERVER_ERROR, format!("corrupt persisted state: {}", what)),
+        StorageError::Io(what) => (StatusCode::INTERNAL_SERVER_ERROR, format!("storage io error: {}", what)),
+        StorageError::Conflict(what) => (StatusCode::CONFLICT, format!("already exists: {}", what)),
+    }
+}
+
+/// Where the raw bytes for a persisted key (e.g. `"accounts"`, `"strategies"`)
+/// actually live. `AccountState`/`TradingState` don't know or care which
+/// backend they're on — they just serialize themselves and hand the bytes
+/// over, so swapping backends never touches handler code.
+pub trait PersistenceBackend: Send + Sync {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+    fn write(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError>;
+}
+
+/// Keeps everything in a `HashMap` for the lifetime of the process, so state
+/// is lost on restart. It sits behind the same interface as `FileBackend`,
+/// so switching `main.rs` to it is a one-line swap.
+#[derive(Default)]
+pub struct InMemoryBackend {
+    data: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl InMemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PersistenceBackend for InMemoryBackend {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.data.lock().unwrap().get(key).cloned())
+    }
+
+    fn write(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError> {
+        self.data.lock().unwrap().insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// Persists each key as its own file under `dir`, so the server survives a
+/// restart. Writes go through a temp file + rename so a crash mid-write
+/// can't leave a half-written, unparseable file behind.
+pub struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(dir: PathBuf) -> Result<Self, StorageError> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", key))
+    }
+}
+
+impl PersistenceBackend for FileBackend {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        match fs::read(self.path_for(key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError> {
+        let final_path = self.path_for(key);
+        let tmp_path = self.dir.join(format!("{}.bin.tmp", key));
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(&tmp_path, &final_path)?;
+        Ok(())
+    }
+}
+
+/// Implemented by each piece of server state that needs to survive a
+/// restart. `load` rebuilds the state from whatever `backend` has under
+/// `key`, returning `StorageError::NotFound` on a cold start; `persist`
+/// writes the current state back out under the same key.
+pub trait Storage: Sized {
+    const KEY: &'static str;
+
+    fn load(backend: &dyn PersistenceBackend) -> Result<Self, StorageError>;
+    fn persist(&self, backend: &dyn PersistenceBackend) -> Result<(), StorageError>;
+}